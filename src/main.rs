@@ -7,33 +7,31 @@ use typed_arena::Arena;
 fn main() {
     let arena = Arena::with_capacity(1024);
 
+    // 0\d
+    let zero_digit: Vec<&Regex> = vec![
+        arena.alloc(Regex::Literal(Char::Just('0'))),
+        arena.alloc(Regex::Literal(Char::Number)),
+    ];
+
     // Expected: Equivalent to \w{5}you(0\d){2,}
-    let regex = Regex::sequence_from_iter(
-        &arena,
-        vec![
-            // \w{5}
-            arena.alloc(Regex::Repeat {
-                expr: arena.alloc(Regex::Literal(Char::Alphabet)),
-                min: 5,
-                max: Some(5),
-            }),
-            // y o u
-            arena.alloc(Regex::Literal(Char::Just('y'))),
-            arena.alloc(Regex::Literal(Char::Just('o'))),
-            arena.alloc(Regex::Literal(Char::Just('u'))),
-            // (0\d){2,}
-            arena.alloc(Regex::Repeat {
-                expr: Regex::sequence_from_iter(
-                    &arena,
-                    vec![
-                        arena.alloc(Regex::Literal(Char::Just('0'))),
-                        arena.alloc(Regex::Literal(Char::Number)),
-                    ],
-                ),
-                min: 2,
-                max: None,
-            }),
-        ],
-    );
+    let items: Vec<&Regex> = vec![
+        // \w{5}
+        arena.alloc(Regex::Repeat {
+            expr: arena.alloc(Regex::Literal(Char::Alphabet)),
+            min: 5,
+            max: Some(5),
+        }),
+        // y o u
+        arena.alloc(Regex::Literal(Char::Just('y'))),
+        arena.alloc(Regex::Literal(Char::Just('o'))),
+        arena.alloc(Regex::Literal(Char::Just('u'))),
+        // (0\d){2,}
+        arena.alloc(Regex::Repeat {
+            expr: Regex::sequence_from_iter(&arena, zero_digit),
+            min: 2,
+            max: None,
+        }),
+    ];
+    let regex = Regex::sequence_from_iter(&arena, items);
     println!("{}", regex);
 }
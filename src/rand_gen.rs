@@ -1,21 +1,21 @@
-use crate::regex::{Char, Regex};
+use crate::regex::{Alphabet, Char, Regex};
 
 use std::io::{prelude::*, Result as IoResult};
 
 use rand::{prelude::*, seq::SliceRandom};
 
-const RANDOM_CHARS: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
-
 pub struct RandomGenerator<R> {
     rng: R,
     quantity_upper: usize,
+    alphabet: Alphabet,
 }
 
 impl<R: Rng> RandomGenerator<R> {
-    pub fn new(rng: R, quantity_upper: usize) -> RandomGenerator<R> {
+    pub fn new(rng: R, quantity_upper: usize, alphabet: Alphabet) -> RandomGenerator<R> {
         RandomGenerator {
             rng,
             quantity_upper,
+            alphabet,
         }
     }
 
@@ -31,16 +31,32 @@ impl<R: Rng> RandomGenerator<R> {
             Regex::Literal(c) => match c {
                 Char::Just(c) => write!(writer, "{}", c)?,
                 Char::Alphabet => {
-                    let index = self.rng.gen_range(0..26);
-                    write!(writer, "{}", &RANDOM_CHARS[index..(index + 1)])?;
+                    let c = self
+                        .alphabet
+                        .word
+                        .choose(&mut self.rng)
+                        .expect("Word alphabet should not be empty");
+                    write!(writer, "{}", c)?;
                 }
                 Char::Number => {
-                    let index = self.rng.gen_range(26..36);
-                    write!(writer, "{}", &RANDOM_CHARS[index..(index + 1)])?;
+                    let c = self
+                        .alphabet
+                        .digit
+                        .choose(&mut self.rng)
+                        .expect("Digit alphabet should not be empty");
+                    write!(writer, "{}", c)?;
                 }
                 Char::Any => {
-                    let index = self.rng.gen_range(0..36);
-                    write!(writer, "{}", &RANDOM_CHARS[index..(index + 1)])?;
+                    let c = self
+                        .alphabet
+                        .any
+                        .choose(&mut self.rng)
+                        .expect("Any alphabet should not be empty");
+                    write!(writer, "{}", c)?;
+                }
+                Char::Set { .. } => {
+                    let index = self.rng.gen_range(0..c.random_weight(&self.alphabet));
+                    write!(writer, "{}", c.nth_in_set(index))?;
                 }
             },
             Regex::Sequence { .. } => {
@@ -52,7 +68,7 @@ impl<R: Rng> RandomGenerator<R> {
                 let items: Vec<_> = regex
                     .iter()
                     .expect("Should have items")
-                    .map(|r| (r, r.random_weight()))
+                    .map(|r| (r, r.random_weight(&self.alphabet)))
                     .collect();
                 let (item, _) = items
                     .choose_weighted(&mut self.rng, |x| x.1)
@@ -66,6 +82,57 @@ impl<R: Rng> RandomGenerator<R> {
                     self.write_regex(writer, expr)?;
                 }
             }
+            Regex::Dead => unreachable!("Dead is only produced by derivative matching"),
+        }
+        Ok(())
+    }
+
+    /// Generates a string sampled across the whole bounded language
+    /// described by `regex`, rather than structurally (which skews toward
+    /// branches/lengths that happen to expand into fewer distinct strings).
+    /// Sampling is uniform over the ways `regex` can emit a string (see
+    /// `Regex::count`), so a string an ambiguous pattern can emit in several
+    /// ways is proportionally more likely.
+    pub fn generate_uniform(&mut self, regex: &Regex<'_>) -> IoResult<String> {
+        let mut buffer = vec![];
+        self.write_regex_uniform(&mut buffer, regex)?;
+        Ok(String::from_utf8(buffer).expect("Should contain only UTF-8"))
+    }
+
+    fn write_regex_uniform<W: Write>(&mut self, writer: &mut W, regex: &Regex<'_>) -> IoResult<()> {
+        match regex {
+            Regex::Tail => (),
+            Regex::Literal(_) => self.write_regex(writer, regex)?,
+            Regex::Sequence { .. } => {
+                for item in regex.iter().expect("Should have items") {
+                    self.write_regex_uniform(writer, item)?;
+                }
+            }
+            Regex::AnyOf { .. } => {
+                let items: Vec<_> = regex
+                    .iter()
+                    .expect("Should have items")
+                    .map(|r| (r, r.count(self.quantity_upper, &self.alphabet)))
+                    .collect();
+                let (item, _) = items
+                    .choose_weighted(&mut self.rng, |x| x.1)
+                    .expect("Should have at least one item");
+                self.write_regex_uniform(writer, item)?;
+            }
+            Regex::Repeat { expr, min, max } => {
+                let upper = max.unwrap_or(self.quantity_upper);
+                let base = expr.count(self.quantity_upper, &self.alphabet);
+                let lengths: Vec<_> = (*min..=upper)
+                    .map(|k| (k, base.saturating_pow(k as u32)))
+                    .collect();
+                let (k, _) = lengths
+                    .choose_weighted(&mut self.rng, |x| x.1)
+                    .expect("Should have at least one candidate length");
+                for _ in 0..*k {
+                    self.write_regex_uniform(writer, expr)?;
+                }
+            }
+            Regex::Dead => unreachable!("Dead is only produced by derivative matching"),
         }
         Ok(())
     }
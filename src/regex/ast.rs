@@ -7,6 +7,39 @@ use std::{
 
 use typed_arena::Arena;
 
+/// Defines the pools of characters sampled for `\w`, `\d`, and `.` (any), and
+/// matched by those same classes during derivative matching.
+#[derive(Debug, Clone)]
+pub struct Alphabet {
+    pub(crate) word: Vec<char>,
+    pub(crate) digit: Vec<char>,
+    pub(crate) any: Vec<char>,
+}
+
+impl Alphabet {
+    /// Creates an `Alphabet` from explicit word, digit, and "any" sets.
+    pub fn new(word: Vec<char>, digit: Vec<char>, any: Vec<char>) -> Alphabet {
+        Alphabet { word, digit, any }
+    }
+}
+
+impl Default for Alphabet {
+    /// The classic ASCII alphabet: `[a-z]`, `[0-9]`, and their union for `.`.
+    fn default() -> Alphabet {
+        let word: Vec<char> = ('a'..='z').collect();
+        let digit: Vec<char> = ('0'..='9').collect();
+        let any: Vec<char> = word.iter().chain(digit.iter()).copied().collect();
+        Alphabet { word, digit, any }
+    }
+}
+
+/// Largest `max - min` for a bounded `Repeat` that `Regex::to_ebnf` will
+/// expand into an explicit alternation, one arm per admissible count. Beyond
+/// this, expansion falls back to the compact (imprecise) notation, since
+/// `to_ebnf` takes no `quantity_upper`-style cap and a pattern like
+/// `a{0,50000000}` would otherwise build a string of unbounded size.
+const EBNF_REPEAT_EXPANSION_LIMIT: usize = 64;
+
 /// Represents a literal character in regex.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Char {
@@ -18,15 +51,143 @@ pub enum Char {
 
     /// numbers `[0-9]`
     Number,
+
+    /// any character `.`, drawn from the alphabet's "any" set
+    Any,
+
+    /// character class `[XYZ]`, `[X-Z]`, or `[^XYZ]`
+    Set { ranges: Vec<(char, char)>, negated: bool },
 }
 
 impl Char {
     /// Returns the weight of this `Char` instance for random generation.
-    pub fn random_weight(&self) -> usize {
+    pub fn random_weight(&self, alphabet: &Alphabet) -> usize {
         match self {
-            Char::Just(c) => 1,
-            Char::Alphabet => 26,
-            Char::Number => 10,
+            Char::Just(_) => 1,
+            Char::Alphabet => alphabet.word.len(),
+            Char::Number => alphabet.digit.len(),
+            Char::Any => alphabet.any.len(),
+            Char::Set { ranges, negated } => Self::resolved_ranges(ranges, *negated)
+                .iter()
+                .map(|(lo, hi)| (hi - lo + 1) as usize)
+                .sum(),
+        }
+    }
+
+    /// Returns the `index`-th code point (in ascending order) matched by this
+    /// `Char::Set`. Panics for any other variant.
+    pub fn nth_in_set(&self, index: usize) -> char {
+        match self {
+            Char::Set { ranges, negated } => {
+                Self::nth_code_point(&Self::resolved_ranges(ranges, *negated), index)
+            }
+            _ => unreachable!("nth_in_set called on a non-Set Char"),
+        }
+    }
+
+    /// Merges the given ranges and, if `negated`, complements them against the
+    /// full `char` scalar value range (skipping the UTF-16 surrogate gap).
+    fn resolved_ranges(ranges: &[(char, char)], negated: bool) -> Vec<(u32, u32)> {
+        let mut sorted: Vec<(u32, u32)> =
+            ranges.iter().map(|&(lo, hi)| (lo as u32, hi as u32)).collect();
+        sorted.sort_unstable();
+
+        let mut merged: Vec<(u32, u32)> = vec![];
+        for (lo, hi) in sorted {
+            match merged.last_mut() {
+                Some(last) if lo <= last.1.saturating_add(1) => last.1 = last.1.max(hi),
+                _ => merged.push((lo, hi)),
+            }
+        }
+
+        if !negated {
+            return merged;
+        }
+
+        let mut complement = vec![];
+        let mut cursor = 0u32;
+        for (lo, hi) in merged {
+            if cursor < lo {
+                complement.push((cursor, lo - 1));
+            }
+            cursor = hi + 1;
+        }
+        if cursor <= char::MAX as u32 {
+            complement.push((cursor, char::MAX as u32));
+        }
+
+        // `char` cannot represent the UTF-16 surrogate range, so split any
+        // complement range that straddles it.
+        complement
+            .into_iter()
+            .flat_map(|(lo, hi)| {
+                if hi < 0xD800 || lo > 0xDFFF {
+                    vec![(lo, hi)]
+                } else {
+                    let mut parts = vec![];
+                    if lo < 0xD800 {
+                        parts.push((lo, 0xD7FF));
+                    }
+                    if hi > 0xDFFF {
+                        parts.push((0xE000, hi));
+                    }
+                    parts
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the `index`-th code point across the given ascending, non-overlapping ranges.
+    fn nth_code_point(ranges: &[(u32, u32)], mut index: usize) -> char {
+        for &(lo, hi) in ranges {
+            let span = (hi - lo + 1) as usize;
+            if index < span {
+                return char::from_u32(lo + index as u32).expect("Should be a valid char");
+            }
+            index -= span;
+        }
+        unreachable!("Index out of range for char set")
+    }
+
+    /// Returns whether `c` belongs to this char class. Used by the Brzozowski
+    /// derivative matcher.
+    pub fn matches_char(&self, c: char, alphabet: &Alphabet) -> bool {
+        match self {
+            Char::Just(expected) => *expected == c,
+            Char::Alphabet => alphabet.word.contains(&c),
+            Char::Number => alphabet.digit.contains(&c),
+            Char::Any => alphabet.any.contains(&c),
+            Char::Set { ranges, negated } => {
+                let in_ranges = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+                in_ranges != *negated
+            }
+        }
+    }
+
+    /// Renders this char class as an EBNF terminal.
+    pub fn to_ebnf(&self) -> String {
+        match self {
+            Char::Just(c) => format!("\"{}\"", c),
+            Char::Alphabet => "WORD_CHAR".to_string(),
+            Char::Number => "DIGIT".to_string(),
+            Char::Any => "ANY".to_string(),
+            Char::Set { ranges, negated } => {
+                let mut rendered = String::from("[");
+                if *negated {
+                    rendered.push('^');
+                }
+                for &(lo, hi) in ranges {
+                    if lo == hi {
+                        rendered.push(lo);
+                    } else {
+                        rendered.push(lo);
+                        rendered.push('-');
+                        rendered.push(hi);
+                    }
+                }
+                rendered.push(']');
+                rendered
+            }
         }
     }
 }
@@ -37,6 +198,21 @@ impl Display for Char {
             Char::Just(c) => write!(f, "{}", c),
             Char::Alphabet => write!(f, "\\w"),
             Char::Number => write!(f, "\\d"),
+            Char::Any => write!(f, "."),
+            Char::Set { ranges, negated } => {
+                write!(f, "[")?;
+                if *negated {
+                    write!(f, "^")?;
+                }
+                for &(lo, hi) in ranges {
+                    if lo == hi {
+                        write!(f, "{}", lo)?;
+                    } else {
+                        write!(f, "{}-{}", lo, hi)?;
+                    }
+                }
+                write!(f, "]")
+            }
         }
     }
 }
@@ -68,13 +244,17 @@ pub enum Regex<'a> {
         min: usize,
         max: Option<usize>,
     },
+
+    /// The empty language `∅`, matching nothing. Only ever produced as a
+    /// Brzozowski derivative; never constructed by the parser.
+    Dead,
 }
 
 impl<'a> Regex<'a> {
     /// Constructs `Regex::Sequence` list from iterator.
     pub fn sequence_from_iter(
         arena: &'a Arena<Regex<'a>>,
-        iter: impl IntoIterator<Item = &'a mut Regex<'a>>,
+        iter: impl IntoIterator<Item = &'a Regex<'a>>,
     ) -> &'a Regex<'a> {
         let mut iter: Vec<_> = iter.into_iter().collect();
         iter.reverse();
@@ -82,7 +262,7 @@ impl<'a> Regex<'a> {
             0 => arena.alloc(Regex::Tail),
             1 => iter.into_iter().next().expect("Should have just one item"),
             _ => {
-                let mut rest = arena.alloc(Regex::Tail);
+                let mut rest: &'a Regex<'a> = arena.alloc(Regex::Tail);
                 for head in iter {
                     rest = arena.alloc(Regex::Sequence { head, rest });
                 }
@@ -94,7 +274,7 @@ impl<'a> Regex<'a> {
     /// Constructs `Regex::AnyOf` list from iterator.
     pub fn anyof_from_iter(
         arena: &'a Arena<Regex<'a>>,
-        iter: impl IntoIterator<Item = &'a mut Regex<'a>>,
+        iter: impl IntoIterator<Item = &'a Regex<'a>>,
     ) -> &'a Regex<'a> {
         let mut iter: Vec<_> = iter.into_iter().collect();
         iter.reverse();
@@ -102,7 +282,7 @@ impl<'a> Regex<'a> {
             0 => arena.alloc(Regex::Tail),
             1 => iter.into_iter().next().expect("Should have just one item"),
             _ => {
-                let mut rest = arena.alloc(Regex::Tail);
+                let mut rest: &'a Regex<'a> = arena.alloc(Regex::Tail);
                 for head in iter {
                     rest = arena.alloc(Regex::AnyOf { head, rest });
                 }
@@ -133,6 +313,188 @@ impl<'a> Regex<'a> {
                 _ => false,
             },
             Regex::Repeat { .. } => false,
+            Regex::Dead => false,
+        }
+    }
+
+    /// Returns the weight of this node for weighted random selection among `AnyOf` branches.
+    pub fn random_weight(&self, alphabet: &Alphabet) -> usize {
+        match self {
+            Regex::Tail => 1,
+            Regex::Literal(c) => c.random_weight(alphabet),
+            Regex::Sequence { .. } => self
+                .iter()
+                .expect("Should have items")
+                .map(|r| r.random_weight(alphabet))
+                .product(),
+            Regex::AnyOf { .. } => self
+                .iter()
+                .expect("Should have items")
+                .map(|r| r.random_weight(alphabet))
+                .sum(),
+            Regex::Repeat { min, max, .. } => max.unwrap_or(*min).max(1),
+            Regex::Dead => 0,
+        }
+    }
+
+    /// Counts the number of ways this node can emit a string, given the cap
+    /// applied to unbounded `Repeat`s. A string that can be emitted in more
+    /// than one way (e.g. by `a|a`) is counted once per way, so this equals
+    /// the number of distinct strings only for unambiguous patterns.
+    /// Saturates instead of overflowing, since bounded repeats can easily
+    /// describe an astronomical language.
+    pub fn count(&self, quantity_upper: usize, alphabet: &Alphabet) -> u128 {
+        match self {
+            Regex::Tail => 1,
+            Regex::Literal(c) => c.random_weight(alphabet) as u128,
+            Regex::Sequence { .. } => self
+                .iter()
+                .expect("Should have items")
+                .fold(1u128, |acc, r| acc.saturating_mul(r.count(quantity_upper, alphabet))),
+            Regex::AnyOf { .. } => self
+                .iter()
+                .expect("Should have items")
+                .fold(0u128, |acc, r| acc.saturating_add(r.count(quantity_upper, alphabet))),
+            Regex::Repeat { expr, min, max } => {
+                let upper = max.unwrap_or(quantity_upper);
+                let base = expr.count(quantity_upper, alphabet);
+                (*min..=upper).fold(0u128, |acc, k| {
+                    acc.saturating_add(base.saturating_pow(k as u32))
+                })
+            }
+            Regex::Dead => 0,
+        }
+    }
+
+    /// Returns whether this node matches the empty string.
+    fn nullable(&self) -> bool {
+        match self {
+            Regex::Dead => false,
+            Regex::Tail => true,
+            Regex::Literal(_) => false,
+            Regex::Sequence { head, rest } => head.nullable() && rest.nullable(),
+            // A bare `Tail` as `rest` only terminates the list; it is not a member.
+            Regex::AnyOf { head, rest } => {
+                head.nullable() || (!matches!(rest, Regex::Tail) && rest.nullable())
+            }
+            Regex::Repeat { expr, min, .. } => *min == 0 || expr.nullable(),
+        }
+    }
+
+    /// Computes the Brzozowski derivative of this node with respect to `c`:
+    /// the regex matching exactly the suffixes, of strings matched by `self`,
+    /// that start with `c`. Unbounded `Repeat`s are resolved against
+    /// `quantity_upper`, mirroring the cap `RandomGenerator` already applies.
+    fn derivative<'b>(
+        &'b self,
+        arena: &'b Arena<Regex<'b>>,
+        c: char,
+        quantity_upper: usize,
+        alphabet: &Alphabet,
+    ) -> &'b Regex<'b> {
+        match self {
+            Regex::Dead => arena.alloc(Regex::Dead),
+            Regex::Tail => arena.alloc(Regex::Dead),
+            Regex::Literal(chr) => {
+                if chr.matches_char(c, alphabet) {
+                    arena.alloc(Regex::Tail)
+                } else {
+                    arena.alloc(Regex::Dead)
+                }
+            }
+            Regex::Sequence { head, rest } => {
+                let head_deriv = arena.alloc(Regex::Sequence {
+                    head: head.derivative(arena, c, quantity_upper, alphabet),
+                    rest,
+                });
+                if head.nullable() {
+                    arena.alloc(Regex::AnyOf {
+                        head: head_deriv,
+                        rest: rest.derivative(arena, c, quantity_upper, alphabet),
+                    })
+                } else {
+                    head_deriv
+                }
+            }
+            Regex::AnyOf { head, rest } => arena.alloc(Regex::AnyOf {
+                head: head.derivative(arena, c, quantity_upper, alphabet),
+                rest: rest.derivative(arena, c, quantity_upper, alphabet),
+            }),
+            Regex::Repeat { expr, min, max } => {
+                let upper = max.unwrap_or(quantity_upper);
+                if upper == 0 {
+                    return arena.alloc(Regex::Dead);
+                }
+                arena.alloc(Regex::Sequence {
+                    head: expr.derivative(arena, c, quantity_upper, alphabet),
+                    rest: arena.alloc(Regex::Repeat {
+                        expr,
+                        min: min.saturating_sub(1),
+                        max: Some(upper.saturating_sub(1)),
+                    }),
+                })
+            }
+        }
+    }
+
+    /// Tests whether `s` is matched by this regex, using Brzozowski
+    /// derivatives. `quantity_upper` bounds unbounded `Repeat`s the same way
+    /// `RandomGenerator` does, and `alphabet` must match the one used to
+    /// generate `s`, so this can validate its output.
+    pub fn matches(&self, s: &str, quantity_upper: usize, alphabet: &Alphabet) -> bool {
+        let arena = Arena::new();
+        let mut current: &Regex = self;
+        for c in s.chars() {
+            current = current.derivative(&arena, c, quantity_upper, alphabet);
+            if matches!(current, Regex::Dead) {
+                return false;
+            }
+        }
+        current.nullable()
+    }
+
+    /// Renders this node as an EBNF-style production, for feeding the parsed
+    /// structure into grammar tooling instead of re-deriving it from the
+    /// `Display` regex syntax.
+    pub fn to_ebnf(&self) -> String {
+        match self {
+            Regex::Tail => String::new(),
+            Regex::Dead => "\u{2205}".to_string(),
+            Regex::Literal(c) => c.to_ebnf(),
+            Regex::Sequence { .. } => self
+                .iter()
+                .expect("Should have items")
+                .map(Regex::to_ebnf)
+                .collect::<Vec<_>>()
+                .join(" "),
+            Regex::AnyOf { .. } => {
+                let items: Vec<_> = self.iter().expect("Should have items").map(Regex::to_ebnf).collect();
+                format!("( {} )", items.join(" | "))
+            }
+            Regex::Repeat { expr, min, max } => {
+                let inner = expr.to_ebnf();
+                match (*min, *max) {
+                    (0, Some(1)) => format!("[ {} ]", inner),
+                    (0, None) => format!("{{ {} }}", inner),
+                    (1, None) => format!("{} {{ {} }}", inner, inner),
+                    (n, Some(m)) if n == m => format!("{} * ( {} )", n, inner),
+                    (n, None) => format!("{} * ( {} ) {{ {} }}", n, inner, inner),
+                    (n, Some(m)) if m - n <= EBNF_REPEAT_EXPANSION_LIMIT => {
+                        // Every count in `[n, m]` is admissible, so render an
+                        // explicit alternation; `n * ( x ) [ (m-n) * ( x ) ]`
+                        // would only admit exactly `n` or exactly `m`.
+                        let counts: Vec<_> = (n..=m).map(|k| format!("{} * ( {} )", k, inner)).collect();
+                        format!("( {} )", counts.join(" | "))
+                    }
+                    (n, Some(m)) => {
+                        // The full alternation would be too large to build;
+                        // fall back to the compact (imprecise) notation
+                        // rather than spending unbounded time/memory on a
+                        // single `to_ebnf()` call.
+                        format!("{} * ( {} ) [ {} * ( {} ) ]", n, inner, m - n, inner)
+                    }
+                }
+            }
         }
     }
 }
@@ -165,6 +527,7 @@ impl<'a> Display for Regex<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             Regex::Tail => Ok(()),
+            Regex::Dead => write!(f, "\u{2205}"),
             Regex::Literal(c) => c.fmt(f),
             Regex::Sequence { .. } => {
                 let items = self.iter().expect("Should have iterator");
@@ -199,6 +562,7 @@ impl<'a> Display for Regex<'a> {
             Regex::Repeat { expr, min, max } => {
                 match expr {
                     Regex::Tail => write!(f, "()")?,
+                    Regex::Dead => write!(f, "\u{2205}")?,
                     Regex::Literal(c) => c.fmt(f)?,
                     Regex::Sequence { .. } => {
                         write!(f, "(")?;
@@ -233,3 +597,55 @@ impl<'a> Display for Regex<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_repeat_rejects_too_many_reps() {
+        let arena = Arena::new();
+        // a{2}
+        let regex = arena.alloc(Regex::Repeat {
+            expr: arena.alloc(Regex::Literal(Char::Just('a'))),
+            min: 2,
+            max: Some(2),
+        });
+        let alphabet = Alphabet::default();
+
+        assert!(regex.matches("aa", 5, &alphabet));
+        assert!(!regex.matches("aaa", 5, &alphabet));
+        assert!(!regex.matches("a", 5, &alphabet));
+    }
+
+    #[test]
+    fn exactly_zero_repeat_only_matches_empty_string() {
+        let arena = Arena::new();
+        // a{0}
+        let regex = arena.alloc(Regex::Repeat {
+            expr: arena.alloc(Regex::Literal(Char::Just('a'))),
+            min: 0,
+            max: Some(0),
+        });
+        let alphabet = Alphabet::default();
+
+        assert!(regex.matches("", 5, &alphabet));
+        assert!(!regex.matches("a", 5, &alphabet));
+        assert!(!regex.matches("aa", 5, &alphabet));
+    }
+
+    #[test]
+    fn count_respects_the_configured_alphabet() {
+        let arena = Arena::new();
+        // \w{1,3}
+        let regex = arena.alloc(Regex::Repeat {
+            expr: arena.alloc(Regex::Literal(Char::Alphabet)),
+            min: 1,
+            max: Some(3),
+        });
+        let alphabet = Alphabet::new(vec!['X', 'Y'], ('0'..='9').collect(), vec!['X', 'Y']);
+
+        // 2 one-char strings + 4 two-char strings + 8 three-char strings.
+        assert_eq!(regex.count(5, &alphabet), 14);
+    }
+}
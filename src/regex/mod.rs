@@ -1,5 +1,5 @@
 pub mod ast;
 pub mod parser;
 
-pub use crate::regex::ast::{Char, Regex};
-pub use crate::regex::parser::{ParseError, Parser};
+pub use crate::regex::ast::{Alphabet, Char, Regex};
+pub use crate::regex::parser::{ParseError, Parser, Position};
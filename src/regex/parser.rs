@@ -1,10 +1,9 @@
 /// Contains regex parser.
-use crate::regex::{Char, Regex};
+use crate::regex::{Alphabet, Char, Regex};
 
 use std::{
     error::Error,
     fmt::{Display, Formatter, Result as FmtResult},
-    iter::Peekable,
     mem::size_of,
     str::Chars,
     vec,
@@ -12,6 +11,13 @@ use std::{
 
 use typed_arena::Arena;
 
+/// Represents a position in the input pattern, for error reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub char_index: usize,
+    pub byte_offset: usize,
+}
+
 /// Represents an error of regex parser.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ParseError {
@@ -21,31 +27,112 @@ pub enum ParseError {
     UnexpectedChar {
         expected: char,
         actual: char,
+        position: Position,
     },
 
     /// Unexpected control char detected.
-    ShouldEscape,
+    ShouldEscape { position: Position },
 
     /// Unexpected EOS detected.
-    UnexpectedEos,
+    UnexpectedEos { position: Position },
+
+    /// A `[...]` character class range whose end point precedes its start
+    /// point, e.g. `[z-a]`.
+    InvalidRange {
+        start: char,
+        end: char,
+        position: Position,
+    },
+
+    /// A `[]` character class with no members.
+    EmptySet { position: Position },
 }
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             ParseError::AlreadyInUse => write!(f, "Parser already in use"),
-            ParseError::UnexpectedChar { expected, actual } => {
-                write!(f, "Unexpected char '{}', expected '{}'", actual, expected)
-            }
-            ParseError::ShouldEscape => write!(f, "Unexpected control char detected"),
-            ParseError::UnexpectedEos => write!(f, "Unexpected EOS detected"),
-            // _ => write!(f, "Other error happenned"),
+            ParseError::UnexpectedChar {
+                expected,
+                actual,
+                position,
+            } => write!(
+                f,
+                "Unexpected char '{}', expected '{}' at index {}",
+                actual, expected, position.char_index
+            ),
+            ParseError::ShouldEscape { position } => write!(
+                f,
+                "Unexpected control char detected at index {}",
+                position.char_index
+            ),
+            ParseError::UnexpectedEos { position } => write!(
+                f,
+                "Unexpected EOS detected at index {}",
+                position.char_index
+            ),
+            ParseError::InvalidRange {
+                start,
+                end,
+                position,
+            } => write!(
+                f,
+                "Invalid range '{}-{}' at index {}",
+                start, end, position.char_index
+            ),
+            ParseError::EmptySet { position } => write!(
+                f,
+                "Empty character class at index {}",
+                position.char_index
+            ),
         }
     }
 }
 
 impl Error for ParseError {}
 
+/// Wraps a `Chars` iterator, tracking the char index and byte offset of the
+/// cursor so that `ParseError`s can point at the offending character.
+struct Cursor<'s> {
+    chars: Chars<'s>,
+    peeked: Option<char>,
+    char_index: usize,
+    byte_offset: usize,
+}
+
+impl<'s> Cursor<'s> {
+    fn new(re: &'s str) -> Cursor<'s> {
+        let mut chars = re.chars();
+        let peeked = chars.next();
+        Cursor {
+            chars,
+            peeked,
+            char_index: 0,
+            byte_offset: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peeked
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peeked?;
+        self.peeked = self.chars.next();
+        self.char_index += 1;
+        self.byte_offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Returns the position of the char that would be returned by `peek`.
+    fn position(&self) -> Position {
+        Position {
+            char_index: self.char_index,
+            byte_offset: self.byte_offset,
+        }
+    }
+}
+
 /// Regex parser.
 pub struct Parser<'a> {
     arena: Arena<Regex<'a>>,
@@ -63,29 +150,26 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses a regex.
-    pub fn parse(&'a mut self, re: &str) -> Result<(&'a mut Regex<'a>, usize), ParseError> {
+    pub fn parse(&'a mut self, re: &str) -> Result<(&'a Regex<'a>, usize), ParseError> {
         if self.in_use {
             return Err(ParseError::AlreadyInUse);
         }
 
         self.in_use = true;
-        let mut chars = re.chars().peekable();
-        let result = self.parse_expr_list(&mut chars)?;
+        let mut cursor = Cursor::new(re);
+        let result = self.parse_expr_list(&mut cursor)?;
         Ok((result, self.arena.len() * size_of::<Regex>()))
     }
 
     /// Parses `EXPRLIST`.
-    fn parse_expr_list(
-        &'a self,
-        chars: &mut Peekable<Chars>,
-    ) -> Result<&'a mut Regex<'a>, ParseError> {
-        let mut seqs = vec![];
+    fn parse_expr_list(&'a self, chars: &mut Cursor) -> Result<&'a Regex<'a>, ParseError> {
+        let mut seqs: Vec<&'a Regex<'a>> = vec![];
         loop {
             let item = self.parse_expr_seq(chars)?;
             seqs.push(item);
 
             let peeked = chars.peek();
-            if peeked != Some(&'|') {
+            if peeked != Some('|') {
                 break;
             }
             chars.next();
@@ -94,12 +178,10 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses `EXPRSEQ`.
-    fn parse_expr_seq(
-        &'a self,
-        chars: &mut Peekable<Chars>,
-    ) -> Result<&'a mut Regex<'a>, ParseError> {
-        let mut terms = vec![];
+    fn parse_expr_seq(&'a self, chars: &mut Cursor) -> Result<&'a Regex<'a>, ParseError> {
+        let mut terms: Vec<&'a Regex<'a>> = vec![];
         loop {
+            let position = chars.position();
             let item = self.parse_term(chars)?;
             if item.is_none() {
                 break;
@@ -110,7 +192,7 @@ impl<'a> Parser<'a> {
                 Some('+') => {
                     chars.next();
                     terms.push(self.arena.alloc(Regex::Repeat {
-                        expr: item.ok_or(ParseError::ShouldEscape)?,
+                        expr: item.ok_or(ParseError::ShouldEscape { position })?,
                         min: 1,
                         max: None,
                     }));
@@ -118,7 +200,7 @@ impl<'a> Parser<'a> {
                 Some('*') => {
                     chars.next();
                     terms.push(self.arena.alloc(Regex::Repeat {
-                        expr: item.ok_or(ParseError::ShouldEscape)?,
+                        expr: item.ok_or(ParseError::ShouldEscape { position })?,
                         min: 0,
                         max: None,
                     }));
@@ -126,7 +208,7 @@ impl<'a> Parser<'a> {
                 Some('?') => {
                     chars.next();
                     terms.push(self.arena.alloc(Regex::Repeat {
-                        expr: item.ok_or(ParseError::ShouldEscape)?,
+                        expr: item.ok_or(ParseError::ShouldEscape { position })?,
                         min: 0,
                         max: Some(1),
                     }));
@@ -134,13 +216,17 @@ impl<'a> Parser<'a> {
                 Some('{') => {
                     chars.next();
                     let lower = parse_number(chars)?.unwrap_or(0);
-                    match chars.peek().copied().ok_or(ParseError::UnexpectedEos)? {
+                    let brace_position = chars.position();
+                    match chars
+                        .peek()
+                        .ok_or(ParseError::UnexpectedEos { position: brace_position })?
+                    {
                         ',' => {
                             chars.next();
                             let upper = parse_number(chars)?;
                             expect_char(chars, '}')?;
                             terms.push(self.arena.alloc(Regex::Repeat {
-                                expr: item.ok_or(ParseError::ShouldEscape)?,
+                                expr: item.ok_or(ParseError::ShouldEscape { position })?,
                                 min: lower,
                                 max: upper,
                             }));
@@ -148,19 +234,19 @@ impl<'a> Parser<'a> {
                         '}' => {
                             chars.next();
                             terms.push(self.arena.alloc(Regex::Repeat {
-                                expr: item.ok_or(ParseError::ShouldEscape)?,
+                                expr: item.ok_or(ParseError::ShouldEscape { position })?,
                                 min: lower,
                                 max: Some(lower),
                             }));
                         }
-                        _ => return Err(ParseError::ShouldEscape),
+                        _ => return Err(ParseError::ShouldEscape { position: brace_position }),
                     }
                 }
                 Some(_) => {
-                    terms.push(item.ok_or(ParseError::ShouldEscape)?);
+                    terms.push(item.ok_or(ParseError::ShouldEscape { position })?);
                 }
                 None => {
-                    terms.push(item.ok_or(ParseError::ShouldEscape)?);
+                    terms.push(item.ok_or(ParseError::ShouldEscape { position })?);
                     break;
                 }
             }
@@ -169,10 +255,7 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses `TERM`.
-    fn parse_term(
-        &'a self,
-        chars: &mut Peekable<Chars>,
-    ) -> Result<Option<&'a mut Regex<'a>>, ParseError> {
+    fn parse_term(&'a self, chars: &mut Cursor) -> Result<Option<&'a Regex<'a>>, ParseError> {
         match chars.peek() {
             Some('(') => {
                 chars.next();
@@ -181,29 +264,55 @@ impl<'a> Parser<'a> {
                 Ok(Some(expr_list))
             }
             Some('[') => {
+                let set_position = chars.position();
                 chars.next();
-                let mut charlist = vec![];
+                let negated = if chars.peek() == Some('^') {
+                    chars.next();
+                    true
+                } else {
+                    false
+                };
+
+                let mut ranges = vec![];
                 loop {
-                    match self.parse_char(chars)? {
-                        Some(c) => charlist.push(c),
-                        None => {
-                            expect_char(chars, ']')?;
-                            break;
-                        }
+                    if chars.peek() == Some(']') {
+                        chars.next();
+                        break;
                     }
+
+                    let range_position = chars.position();
+                    let start = parse_set_char(chars)?;
+                    let end = if chars.peek() == Some('-') {
+                        chars.next();
+                        parse_set_char(chars)?
+                    } else {
+                        start
+                    };
+                    if end < start {
+                        return Err(ParseError::InvalidRange {
+                            start,
+                            end,
+                            position: range_position,
+                        });
+                    }
+                    ranges.push((start, end));
+                }
+                if ranges.is_empty() {
+                    return Err(ParseError::EmptySet { position: set_position });
                 }
-                Ok(Some(Regex::anyof_from_iter(&self.arena, charlist)))
+                let set = Char::Set { ranges, negated };
+                if set.random_weight(&Alphabet::default()) == 0 {
+                    return Err(ParseError::EmptySet { position: set_position });
+                }
+                Ok(Some(self.arena.alloc(Regex::Literal(set))))
             }
             _ => self.parse_char(chars),
         }
     }
 
     /// Parses `CHAR`.
-    fn parse_char(
-        &'a self,
-        chars: &mut Peekable<Chars>,
-    ) -> Result<Option<&'a mut Regex<'a>>, ParseError> {
-        match chars.peek().copied() {
+    fn parse_char(&'a self, chars: &mut Cursor) -> Result<Option<&'a Regex<'a>>, ParseError> {
+        match chars.peek() {
             None => Ok(None),
             Some('+' | '?' | '*' | '(' | ')' | '[' | ']' | '{' | '}' | '|') => Ok(None),
             Some('.') => {
@@ -211,10 +320,19 @@ impl<'a> Parser<'a> {
                 Ok(Some(self.arena.alloc(Regex::Literal(Char::Any))))
             }
             Some('\\') => {
+                let position = chars.position();
                 chars.next();
-                match chars.next().ok_or(ParseError::UnexpectedEos)? {
+                match chars.next().ok_or(ParseError::UnexpectedEos { position })? {
                     'd' => Ok(Some(self.arena.alloc(Regex::Literal(Char::Number)))),
                     'w' => Ok(Some(self.arena.alloc(Regex::Literal(Char::Alphabet)))),
+                    'u' => {
+                        let c = parse_unicode_escape(chars)?;
+                        Ok(Some(self.arena.alloc(Regex::Literal(Char::Just(c)))))
+                    }
+                    'x' => {
+                        let c = parse_byte_escape(chars)?;
+                        Ok(Some(self.arena.alloc(Regex::Literal(Char::Just(c)))))
+                    }
                     c => Ok(Some(self.arena.alloc(Regex::Literal(Char::Just(c))))),
                 }
             }
@@ -226,38 +344,85 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Parses a single member of a `[...]` character class, honoring `\`-escapes.
+fn parse_set_char(chars: &mut Cursor) -> Result<char, ParseError> {
+    let position = chars.position();
+    match chars.next().ok_or(ParseError::UnexpectedEos { position })? {
+        '\\' => {
+            let position = chars.position();
+            chars.next().ok_or(ParseError::UnexpectedEos { position })
+        }
+        c => Ok(c),
+    }
+}
+
+/// Parses a `\u{XXXX}` Unicode code point escape, after the `u` has been consumed.
+fn parse_unicode_escape(chars: &mut Cursor) -> Result<char, ParseError> {
+    expect_char(chars, '{')?;
+    let mut digits = String::with_capacity(6);
+    loop {
+        let position = chars.position();
+        match chars.next().ok_or(ParseError::UnexpectedEos { position })? {
+            '}' => break,
+            c if c.is_ascii_hexdigit() => digits.push(c),
+            _ => return Err(ParseError::ShouldEscape { position }),
+        }
+    }
+    let position = chars.position();
+    let code =
+        u32::from_str_radix(&digits, 16).map_err(|_| ParseError::ShouldEscape { position })?;
+    char::from_u32(code).ok_or(ParseError::ShouldEscape { position })
+}
+
+/// Parses a `\xNN` byte escape, after the `x` has been consumed.
+fn parse_byte_escape(chars: &mut Cursor) -> Result<char, ParseError> {
+    let mut digits = String::with_capacity(2);
+    for _ in 0..2 {
+        let position = chars.position();
+        match chars.next().ok_or(ParseError::UnexpectedEos { position })? {
+            c if c.is_ascii_hexdigit() => digits.push(c),
+            _ => return Err(ParseError::ShouldEscape { position }),
+        }
+    }
+    let code = u32::from_str_radix(&digits, 16).expect("Already validated hex digits");
+    Ok(char::from_u32(code).expect("Byte value is always a valid char"))
+}
+
 /// Parses number.
-fn parse_number(chars: &mut Peekable<Chars>) -> Result<Option<usize>, ParseError> {
+fn parse_number(chars: &mut Cursor) -> Result<Option<usize>, ParseError> {
     let mut number_str = String::with_capacity(16);
     loop {
-        match chars.peek().copied() {
+        match chars.peek() {
             Some(n @ '0'..='9') => {
                 chars.next();
                 number_str.push(n);
             }
-            Some(_c @ (',' | '}')) => break,
-            Some(c) => {
+            Some(',' | '}') => break,
+            Some(_) => {
+                let position = chars.position();
                 return Err(ParseError::UnexpectedChar {
                     expected: '0',
-                    actual: c,
-                })
+                    actual: chars.peek().expect("Already peeked"),
+                    position,
+                });
             }
-            None => return Err(ParseError::UnexpectedEos),
+            None => return Err(ParseError::UnexpectedEos { position: chars.position() }),
         }
     }
 
     if number_str == "" {
         Ok(None)
     } else {
-        Ok(Some(
-            number_str.parse().map_err(|_| ParseError::ShouldEscape)?,
-        ))
+        Ok(Some(number_str.parse().map_err(|_| ParseError::ShouldEscape {
+            position: chars.position(),
+        })?))
     }
 }
 
 /// Expects specific char on stream.
-fn expect_char(chars: &mut Peekable<Chars>, c: char) -> Result<(), ParseError> {
-    let peeked = *chars.peek().ok_or(ParseError::UnexpectedEos)?;
+fn expect_char(chars: &mut Cursor, c: char) -> Result<(), ParseError> {
+    let position = chars.position();
+    let peeked = chars.peek().ok_or(ParseError::UnexpectedEos { position })?;
     if peeked == c {
         chars.next();
         Ok(())
@@ -265,6 +430,7 @@ fn expect_char(chars: &mut Peekable<Chars>, c: char) -> Result<(), ParseError> {
         Err(ParseError::UnexpectedChar {
             expected: c,
             actual: peeked,
+            position,
         })
     }
 }